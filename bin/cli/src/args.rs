@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
 use komodo_client::api::execute::Execution;
 use serde::Deserialize;
@@ -25,10 +27,43 @@ pub struct CliArgs {
   /// Pass api secret in args instead of creds file
   #[arg(long)]
   pub secret: Option<String>,
+  /// Read the api key from this file.
+  /// Takes precedence over `--key`.
+  #[arg(long, env = "KOMODO_CLI_KEY_FILE")]
+  pub key_file: Option<PathBuf>,
+  /// Read the api secret from this file.
+  /// Takes precedence over `--secret`.
+  #[arg(long, env = "KOMODO_CLI_SECRET_FILE")]
+  pub secret_file: Option<PathBuf>,
 
   /// Always continue on user confirmation prompts.
   #[arg(long, short, default_value_t = false)]
   pub yes: bool,
+
+  /// Disable colored output.
+  /// Also respects the `NO_COLOR` environment variable.
+  #[arg(long, default_value_t = false)]
+  pub no_color: bool,
+
+  /// Timeout for requests to Komodo Core, in seconds.
+  /// Set to 0 to disable.
+  #[arg(
+    long,
+    env = "KOMODO_CLI_TIMEOUT_SECONDS",
+    default_value_t = 30
+  )]
+  pub timeout: u64,
+
+  /// Number of times to retry reads against Komodo Core
+  /// when the connection fails or times out, with exponential backoff.
+  /// Executions are never retried.
+  #[arg(long, env = "KOMODO_CLI_RETRIES", default_value_t = 0)]
+  pub retries: u32,
+
+  /// Write the command result to this file as JSON,
+  /// instead of printing it to stdout.
+  #[arg(long, short)]
+  pub output_file: Option<String>,
 }
 
 fn default_creds() -> String {
@@ -43,13 +78,58 @@ pub enum Command {
   Execute {
     #[command(subcommand)]
     execution: Execution,
+    /// Print the execution request as JSON and exit,
+    /// without contacting Komodo Core.
+    #[arg(long, default_value_t = false)]
+    explain: bool,
+  },
+  /// Runs an execution defined in a JSON or TOML file.
+  ///
+  /// The file uses the same shape as the API, eg:
+  /// `type = "RunBuild"` and `params.build = "my-build"`
+  ExecuteFile {
+    /// The path to the execution file.
+    path: String,
+    /// Print the execution request as JSON and exit,
+    /// without contacting Komodo Core.
+    #[arg(long, default_value_t = false)]
+    explain: bool,
+  },
+  /// Prints a shell completion script to stdout.
+  ///
+  /// Source the output, or write it to your shell's
+  /// completion directory, eg:
+  /// `komodo completions zsh > ~/.zfunc/_komodo`
+  Completions {
+    /// The shell to generate completions for
+    shell: clap_complete::Shell,
   },
   // Room for more
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CredsFile {
+  #[serde(flatten)]
+  pub primary: Credentials,
+  /// Additional Komodo Core hosts to fail over to, in order,
+  /// if the primary `url` is unreachable.
+  #[serde(default)]
+  pub hosts: Vec<Credentials>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Credentials {
   pub url: String,
+  #[serde(default)]
   pub key: String,
+  #[serde(default)]
   pub secret: String,
+  /// Read the api key from this file.
+  /// Takes precedence over `key`.
+  #[serde(default)]
+  pub key_file: Option<PathBuf>,
+  /// Read the api secret from this file.
+  /// Takes precedence over `secret`.
+  #[serde(default)]
+  pub secret_file: Option<PathBuf>,
 }
@@ -1,5 +1,6 @@
 use std::time::Duration;
 
+use anyhow::{Context, anyhow};
 use colored::Colorize;
 use komodo_client::{
   api::execute::{BatchExecutionResponse, Execution},
@@ -7,8 +8,8 @@ use komodo_client::{
 };
 
 use crate::{
-  helpers::wait_for_enter,
-  state::{cli_args, komodo_client},
+  helpers::{timeout_context, wait_for_enter},
+  state::{cli_args, komodo_client, komodo_url},
 };
 
 pub enum ExecutionResult {
@@ -16,6 +17,20 @@ pub enum ExecutionResult {
   Batch(BatchExecutionResponse),
 }
 
+/// Parses an [Execution] from a JSON or TOML file,
+/// using the `type` / `params` shape of the API.
+pub fn parse_execution_file(path: &str) -> anyhow::Result<Execution> {
+  merge_config_files::parse_config_file::<Execution>(path).map_err(
+    |e| anyhow!("Failed to parse execution file at {path} | {e:#}"),
+  )
+}
+
+/// The execution request as JSON, to print without running it.
+pub fn explain(execution: &Execution) -> anyhow::Result<String> {
+  serde_json::to_string_pretty(execution)
+    .context("Failed to serialize execution")
+}
+
 pub async fn run(execution: Execution) -> anyhow::Result<()> {
   if matches!(execution, Execution::None(_)) {
     println!("Got 'none' execution. Doing nothing...");
@@ -478,15 +493,54 @@ pub async fn run(execution: Execution) -> anyhow::Result<()> {
     Execution::None(_) => unreachable!(),
   };
 
+  let res = res.map_err(|e| timeout_context(komodo_url(), e))?;
+
+  if let Some(path) = &cli_args().output_file {
+    let contents = match &res {
+      ExecutionResult::Single(update) => {
+        serde_json::to_string_pretty(update)
+      }
+      ExecutionResult::Batch(update) => {
+        serde_json::to_string_pretty(update)
+      }
+    }
+    .context("Failed to serialize execution result")?;
+    std::fs::write(path, contents).with_context(|| {
+      format!("Failed to write execution result to {path}")
+    })?;
+    println!("\n{}: Wrote result to {path}", "SUCCESS".green());
+    return Ok(());
+  }
+
   match res {
-    Ok(ExecutionResult::Single(update)) => {
+    ExecutionResult::Single(update) => {
       println!("\n{}: {update:#?}", "SUCCESS".green())
     }
-    Ok(ExecutionResult::Batch(update)) => {
+    ExecutionResult::Batch(update) => {
       println!("\n{}: {update:#?}", "SUCCESS".green())
     }
-    Err(e) => println!("{}\n\n{e:#?}", "ERROR".red()),
   }
 
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use komodo_client::api::execute::{Execution, RunBuild};
+  use serde_json::json;
+
+  use super::explain;
+
+  #[test]
+  fn explain_uses_api_shape() {
+    let execution = Execution::RunBuild(RunBuild {
+      build: String::from("test_build"),
+    });
+    let explained: serde_json::Value =
+      serde_json::from_str(&explain(&execution).unwrap()).unwrap();
+    assert_eq!(
+      explained,
+      json!({ "type": "RunBuild", "params": { "build": "test_build" } })
+    );
+  }
+}
@@ -1,8 +1,10 @@
-use std::io::Read;
+use std::{io::Read, time::Duration};
 
 use anyhow::Context;
 use colored::Colorize;
 
+use crate::state::cli_args;
+
 pub fn wait_for_enter(press_enter_to: &str) -> anyhow::Result<()> {
   println!(
     "\nPress {} to {}\n",
@@ -15,3 +17,110 @@ pub fn wait_for_enter(press_enter_to: &str) -> anyhow::Result<()> {
     .context("failed to read ENTER")?;
   Ok(())
 }
+
+/// Adds the Core host and configured timeout to the error
+/// when it was caused by a request timing out.
+pub fn timeout_context(url: &str, e: anyhow::Error) -> anyhow::Error {
+  let timed_out = e.chain().any(|e| {
+    e.downcast_ref::<reqwest::Error>()
+      .is_some_and(reqwest::Error::is_timeout)
+  });
+  if timed_out {
+    e.context(format!(
+      "Request to {url} timed out after {}s",
+      cli_args().timeout
+    ))
+  } else {
+    e
+  }
+}
+
+/// Retries the request with exponential backoff, up to `max_retries` times,
+/// when it fails to connect or times out.
+/// Other errors, like authorization failures, are returned immediately.
+pub async fn with_retries<T, Fut>(
+  max_retries: u32,
+  mut request: impl FnMut() -> Fut,
+) -> anyhow::Result<T>
+where
+  Fut: Future<Output = anyhow::Result<T>>,
+{
+  let mut attempt = 0;
+  loop {
+    match request().await {
+      Ok(res) => return Ok(res),
+      Err(e) if attempt < max_retries && is_retryable(&e) => {
+        attempt += 1;
+        let delay =
+          Duration::from_millis(500 * 2u64.pow(attempt.min(6) - 1));
+        warn!(
+          "Request failed, retrying in {delay:?} ({attempt}/{max_retries}) | {e:#}"
+        );
+        tokio::time::sleep(delay).await;
+      }
+      Err(e) => return Err(e),
+    }
+  }
+}
+
+/// Whether the request failed to connect or timed out.
+pub fn is_retryable(e: &anyhow::Error) -> bool {
+  e.chain().any(|e| {
+    e.downcast_ref::<reqwest::Error>()
+      .is_some_and(|e| e.is_connect() || e.is_timeout())
+  })
+}
+
+/// A real connection error, as nothing listens on port 1.
+#[cfg(test)]
+pub async fn connect_error() -> anyhow::Error {
+  reqwest::get("http://127.0.0.1:1").await.unwrap_err().into()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicU32, Ordering};
+
+  use anyhow::anyhow;
+
+  use super::*;
+
+  #[tokio::test(start_paused = true)]
+  async fn retries_connect_errors_until_success() {
+    let attempts = &AtomicU32::new(0);
+    let res = with_retries(3, || async move {
+      if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+        Err(connect_error().await)
+      } else {
+        Ok("ok")
+      }
+    })
+    .await;
+    assert_eq!(res.unwrap(), "ok");
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn stops_after_max_retries() {
+    let attempts = &AtomicU32::new(0);
+    let res = with_retries(2, || async move {
+      attempts.fetch_add(1, Ordering::SeqCst);
+      Err::<(), _>(connect_error().await)
+    })
+    .await;
+    assert!(res.is_err());
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn does_not_retry_other_errors() {
+    let attempts = &AtomicU32::new(0);
+    let res = with_retries(3, || async move {
+      attempts.fetch_add(1, Ordering::SeqCst);
+      Err::<(), _>(anyhow!("401 Unauthorized"))
+    })
+    .await;
+    assert!(res.is_err());
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+  }
+}
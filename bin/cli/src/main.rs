@@ -1,32 +1,130 @@
 #[macro_use]
 extern crate tracing;
 
+use clap::CommandFactory;
 use colored::Colorize;
 use komodo_client::api::read::GetVersion;
+use reqwest::StatusCode;
 
 mod args;
 mod exec;
 mod helpers;
 mod state;
 
+/// Exit codes:
+/// - `0`: Success
+/// - `1`: Generic error
+/// - `2`: Invalid arguments / usage error (from clap)
+/// - `3`: Authentication / authorization failure
+/// - `4`: The target was not found / nothing matched
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-  tracing_subscriber::fmt().with_target(false).init();
+async fn main() {
+  if let Err(e) = app().await {
+    eprintln!("{}: {e:?}", "ERROR".red());
+    std::process::exit(exit_code(&e));
+  }
+}
+
+fn exit_code(e: &anyhow::Error) -> i32 {
+  match e.downcast_ref::<StatusCode>() {
+    Some(&StatusCode::UNAUTHORIZED | &StatusCode::FORBIDDEN) => 3,
+    Some(&StatusCode::NOT_FOUND) => 4,
+    // Core reports missing resources as
+    // 'did not find any <Resource> matching <name>'
+    _ if format!("{e:#}").contains("did not find any") => 4,
+    _ => 1,
+  }
+}
+
+async fn app() -> anyhow::Result<()> {
+  let no_color = state::cli_args().no_color
+    || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+  if no_color {
+    colored::control::set_override(false);
+  }
+
+  // Completions / explain are written to stdout, and execution files
+  // are validated, before any logging or client initialization.
+  let (execution, explain) = match &state::cli_args().command {
+    args::Command::Completions { shell } => {
+      clap_complete::generate(
+        *shell,
+        &mut args::CliArgs::command(),
+        env!("CARGO_BIN_NAME"),
+        &mut std::io::stdout(),
+      );
+      return Ok(());
+    }
+    args::Command::Execute { execution, explain } => {
+      (execution.to_owned(), *explain)
+    }
+    args::Command::ExecuteFile { path, explain } => {
+      (exec::parse_execution_file(path)?, *explain)
+    }
+  };
+
+  if explain {
+    println!("{}", exec::explain(&execution)?);
+    return Ok(());
+  }
+
+  tracing_subscriber::fmt()
+    .with_target(false)
+    .with_ansi(!no_color)
+    .init();
 
   info!(
     "Komodo CLI version: {}",
     env!("CARGO_PKG_VERSION").blue().bold()
   );
 
+  state::init_komodo_client().await?;
+
   let version =
-    state::komodo_client().read(GetVersion {}).await?.version;
+    helpers::with_retries(state::cli_args().retries, || {
+      state::komodo_client().read(GetVersion {})
+    })
+    .await
+    .map_err(|e| helpers::timeout_context(state::komodo_url(), e))?
+    .version;
   info!("Komodo Core version: {}", version.blue().bold());
 
-  match &state::cli_args().command {
-    args::Command::Execute { execution } => {
-      exec::run(execution.to_owned()).await?
-    }
+  exec::run(execution).await
+}
+
+#[cfg(test)]
+mod tests {
+  use anyhow::anyhow;
+  use reqwest::StatusCode;
+
+  use super::exit_code;
+
+  fn status_error(status: StatusCode) -> anyhow::Error {
+    anyhow!("request failed")
+      .context(status)
+      .context("Failed to run execution")
+  }
+
+  #[test]
+  fn auth_failures_exit_3() {
+    assert_eq!(exit_code(&status_error(StatusCode::UNAUTHORIZED)), 3);
+    assert_eq!(exit_code(&status_error(StatusCode::FORBIDDEN)), 3);
   }
 
-  Ok(())
+  #[test]
+  fn not_found_exits_4() {
+    assert_eq!(exit_code(&status_error(StatusCode::NOT_FOUND)), 4);
+    let e = anyhow!("did not find any Build matching test_build")
+      .context(StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(exit_code(&e), 4);
+  }
+
+  #[test]
+  fn other_errors_exit_1() {
+    assert_eq!(
+      exit_code(&status_error(StatusCode::INTERNAL_SERVER_ERROR)),
+      1
+    );
+    assert_eq!(exit_code(&anyhow!("Failed to parse file")), 1);
+  }
 }
@@ -1,48 +1,381 @@
-use std::sync::OnceLock;
+use std::{path::Path, sync::OnceLock, time::Duration};
 
+use anyhow::{Context, anyhow};
 use clap::Parser;
+use colored::Colorize;
 use komodo_client::KomodoClient;
 use merge_config_files::parse_config_file;
 
-pub fn cli_args() -> &'static crate::args::CliArgs {
-  static CLI_ARGS: OnceLock<crate::args::CliArgs> = OnceLock::new();
-  CLI_ARGS.get_or_init(crate::args::CliArgs::parse)
+use crate::{
+  args::{CliArgs, Credentials, CredsFile},
+  helpers::{is_retryable, timeout_context, with_retries},
+};
+
+pub fn cli_args() -> &'static CliArgs {
+  static CLI_ARGS: OnceLock<CliArgs> = OnceLock::new();
+  CLI_ARGS.get_or_init(CliArgs::parse)
+}
+
+pub fn creds() -> anyhow::Result<&'static CredsFile> {
+  static CREDS: OnceLock<CredsFile> = OnceLock::new();
+  if let Some(creds) = CREDS.get() {
+    return Ok(creds);
+  }
+  let creds = load_creds(cli_args())?;
+  Ok(CREDS.get_or_init(|| creds))
+}
+
+/// Resolves the credentials from args and / or the creds file.
+/// Args take precedence over the creds file,
+/// and `*_file` values take precedence over inline ones.
+fn load_creds(args: &CliArgs) -> anyhow::Result<CredsFile> {
+  let key = match &args.key_file {
+    Some(path) => {
+      Some(read_file(path).context("Invalid --key-file")?)
+    }
+    None => args.key.clone(),
+  };
+  let secret = match &args.secret_file {
+    Some(path) => {
+      Some(read_file(path).context("Invalid --secret-file")?)
+    }
+    None => args.secret.clone(),
+  };
+
+  if let (Some(url), Some(key), Some(secret)) =
+    (&args.url, &key, &secret)
+  {
+    let primary = Credentials {
+      url: url.clone(),
+      key: key.clone(),
+      secret: secret.clone(),
+      key_file: None,
+      secret_file: None,
+    };
+    ensure_credentials(&primary, "args")?;
+    return Ok(CredsFile {
+      primary,
+      hosts: Vec::new(),
+    });
+  }
+
+  let path = args.creds.as_str();
+  let mut creds: CredsFile =
+    parse_config_file(path).map_err(|e| {
+      anyhow!("Failed to parse Komodo credentials at {path} | {e:#}")
+    })?;
+
+  read_credential_files(&mut creds.primary)?;
+  for host in &mut creds.hosts {
+    read_credential_files(host).with_context(|| {
+      format!("Invalid credentials for host {}", host.url)
+    })?;
+  }
+
+  if let Some(url) = &args.url {
+    creds.primary.url.clone_from(url);
+    // Only fail over when the url also came from the creds file,
+    // an explicit `--url` should always be the one targeted.
+    creds.hosts.clear();
+  }
+  if let Some(key) = key {
+    creds.primary.key = key;
+  }
+  if let Some(secret) = secret {
+    creds.primary.secret = secret;
+  }
+
+  let location = format!("creds file {path}");
+  ensure_credentials(&creds.primary, &location)?;
+  for host in &creds.hosts {
+    ensure_credentials(host, &location)?;
+  }
+
+  Ok(creds)
 }
 
+/// Reads `key_file` / `secret_file` into `key` / `secret`, if given.
+fn read_credential_files(
+  creds: &mut Credentials,
+) -> anyhow::Result<()> {
+  if let Some(path) = &creds.key_file {
+    creds.key = read_file(path).context("Invalid key_file")?;
+  }
+  if let Some(path) = &creds.secret_file {
+    creds.secret = read_file(path).context("Invalid secret_file")?;
+  }
+  Ok(())
+}
+
+fn ensure_credentials(
+  creds: &Credentials,
+  location: &str,
+) -> anyhow::Result<()> {
+  anyhow::ensure!(
+    !creds.key.is_empty(),
+    "Missing api key for {} in {location}. Set `key` or `key_file`.",
+    creds.url
+  );
+  anyhow::ensure!(
+    !creds.secret.is_empty(),
+    "Missing api secret for {} in {location}. Set `secret` or `secret_file`.",
+    creds.url
+  );
+  Ok(())
+}
+
+/// Reads a key / secret from a file, ignoring surrounding whitespace.
+fn read_file(path: &Path) -> anyhow::Result<String> {
+  let contents = std::fs::read_to_string(path)
+    .with_context(|| format!("Failed to read file at {path:?}"))?;
+  Ok(contents.trim().to_string())
+}
+
+static KOMODO_CLIENT: OnceLock<KomodoClient> = OnceLock::new();
+static KOMODO_URL: OnceLock<String> = OnceLock::new();
+
+/// Requires [init_komodo_client] to have been called first.
 pub fn komodo_client() -> &'static KomodoClient {
-  static KOMODO_CLIENT: OnceLock<KomodoClient> = OnceLock::new();
-  KOMODO_CLIENT.get_or_init(|| {
-    let args = cli_args();
-    let crate::args::CredsFile { url, key, secret } =
-      match (&args.url, &args.key, &args.secret) {
-        (Some(url), Some(key), Some(secret)) => {
-          crate::args::CredsFile {
-            url: url.clone(),
-            key: key.clone(),
-            secret: secret.clone(),
-          }
-        }
-        (url, key, secret) => {
-          let mut creds: crate::args::CredsFile =
-            parse_config_file(cli_args().creds.as_str())
-              .expect("failed to parse Komodo credentials");
-
-          if let Some(url) = url {
-            creds.url.clone_from(url);
-          }
-          if let Some(key) = key {
-            creds.key.clone_from(key);
-          }
-          if let Some(secret) = secret {
-            creds.secret.clone_from(secret);
-          }
-
-          creds
-        }
-      };
-    futures::executor::block_on(
-      KomodoClient::new(url, key, secret).with_healthcheck(),
-    )
-    .expect("failed to initialize Komodo client")
+  KOMODO_CLIENT
+    .get()
+    .expect("Komodo client used before initialization")
+}
+
+/// The url of the Komodo Core host selected by [init_komodo_client].
+pub fn komodo_url() -> &'static str {
+  KOMODO_URL
+    .get()
+    .expect("Komodo client used before initialization")
+}
+
+/// Initializes the Komodo client, including a health check.
+/// Tries the creds `url`, then each of the creds `hosts` in order,
+/// using the first one which passes the health check.
+/// The `hosts` are skipped when `--url` is passed.
+pub async fn init_komodo_client() -> anyhow::Result<()> {
+  let creds = creds()?;
+  let mut reqwest = reqwest::Client::builder();
+  if cli_args().timeout > 0 {
+    reqwest =
+      reqwest.timeout(Duration::from_secs(cli_args().timeout));
+  }
+  let reqwest = reqwest
+    .build()
+    .context("Failed to initialize http client")?;
+
+  let hosts = std::iter::once(&creds.primary)
+    .chain(&creds.hosts)
+    .map(|host| {
+      (host.url.as_str(), host.key.as_str(), host.secret.as_str())
+    });
+
+  let (url, client) = select_host(hosts, |url, key, secret| {
+    let reqwest = reqwest.clone();
+    async move {
+      with_retries(cli_args().retries, || {
+        KomodoClient::new(url, key, secret)
+          .set_reqwest(reqwest.clone())
+          .with_healthcheck()
+      })
+      .await
+      .map_err(|e| timeout_context(url, e))
+    }
   })
+  .await
+  .context("Failed to initialize Komodo client")?;
+
+  info!("Using Komodo Core at {}", url.blue().bold());
+  KOMODO_URL.get_or_init(|| url.to_string());
+  KOMODO_CLIENT.get_or_init(|| client);
+
+  Ok(())
+}
+
+/// Connects to the first of the (url, key, secret) hosts
+/// which succeeds. Only fails over to the next host when one is
+/// unreachable. Other errors, like authorization failures,
+/// are returned immediately.
+async fn select_host<'a, T, Fut>(
+  hosts: impl IntoIterator<Item = (&'a str, &'a str, &'a str)>,
+  mut connect: impl FnMut(&'a str, &'a str, &'a str) -> Fut,
+) -> anyhow::Result<(&'a str, T)>
+where
+  Fut: Future<Output = anyhow::Result<T>>,
+{
+  let mut error = None;
+  for (url, key, secret) in hosts {
+    if error.is_some() {
+      warn!("Failing over to Komodo Core at {url}");
+    }
+    match connect(url, key, secret).await {
+      Ok(client) => return Ok((url, client)),
+      Err(e) if is_retryable(&e) => {
+        warn!("Komodo Core at {url} is unreachable | {e:#}");
+        error = Some(e);
+      }
+      Err(e) => return Err(e),
+    }
+  }
+  Err(error.unwrap_or_else(|| anyhow!("No Komodo Core hosts")))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{path::PathBuf, sync::Mutex};
+
+  use anyhow::anyhow;
+  use clap::Parser;
+  use reqwest::StatusCode;
+
+  use crate::{args::CliArgs, helpers::connect_error};
+
+  use super::{load_creds, read_file, select_host};
+
+  const HOSTS: [(&str, &str, &str); 2] = [
+    ("http://primary", "key", "secret"),
+    ("http://standby", "key", "secret"),
+  ];
+
+  #[tokio::test]
+  async fn fails_over_when_first_host_is_unreachable() {
+    let attempted = &Mutex::new(Vec::new());
+    let (url, client) = select_host(HOSTS, |url, _, _| async move {
+      attempted.lock().unwrap().push(url);
+      if url == "http://primary" {
+        Err(connect_error().await)
+      } else {
+        Ok("standby client")
+      }
+    })
+    .await
+    .unwrap();
+    assert_eq!(url, "http://standby");
+    assert_eq!(client, "standby client");
+    assert_eq!(
+      *attempted.lock().unwrap(),
+      ["http://primary", "http://standby"]
+    );
+  }
+
+  #[tokio::test]
+  async fn does_not_fail_over_on_auth_error() {
+    let attempted = &Mutex::new(Vec::new());
+    let res = select_host(HOSTS, |url, _, _| async move {
+      attempted.lock().unwrap().push(url);
+      if url == "http://primary" {
+        Err(
+          anyhow!("invalid api key")
+            .context(StatusCode::UNAUTHORIZED),
+        )
+      } else {
+        Ok("standby client")
+      }
+    })
+    .await;
+    assert!(res.is_err());
+    assert_eq!(*attempted.lock().unwrap(), ["http://primary"]);
+  }
+
+  /// Writes the file under a temp folder unique to the test.
+  fn temp_file(test: &str, name: &str, contents: &str) -> PathBuf {
+    let folder = std::env::temp_dir().join(format!(
+      "komodo-cli-creds-{test}-{}",
+      std::process::id()
+    ));
+    std::fs::create_dir_all(&folder).unwrap();
+    let path = folder.join(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+  }
+
+  fn args(args: &[&str]) -> CliArgs {
+    CliArgs::try_parse_from(
+      ["komodo"]
+        .iter()
+        .chain(args)
+        .chain(&["completions", "bash"]),
+    )
+    .unwrap()
+  }
+
+  #[test]
+  fn read_file_trims_whitespace() {
+    let path = temp_file("trim", "key", "  file-key\n");
+    assert_eq!(read_file(&path).unwrap(), "file-key");
+  }
+
+  #[test]
+  fn read_file_errors_on_missing_file() {
+    let path = std::env::temp_dir().join("komodo-cli-missing-key");
+    assert!(read_file(&path).is_err());
+  }
+
+  #[test]
+  fn creds_file_key_file_overrides_inline_key() {
+    let key = temp_file("creds-key-file", "key", "file-key\n");
+    let creds = temp_file(
+      "creds-key-file",
+      "creds.toml",
+      &format!(
+        "url = \"http://komodo\"\nkey = \"inline-key\"\nkey_file = {key:?}\nsecret = \"inline-secret\"\n"
+      ),
+    );
+    let creds =
+      load_creds(&args(&["--creds", creds.to_str().unwrap()]))
+        .unwrap();
+    assert_eq!(creds.primary.key, "file-key");
+    assert_eq!(creds.primary.secret, "inline-secret");
+  }
+
+  #[test]
+  fn key_file_arg_overrides_creds_file_and_key_arg() {
+    let key = temp_file("arg-key-file", "key", "arg-file-key");
+    let secret =
+      temp_file("arg-key-file", "secret", "arg-file-secret");
+    let creds = load_creds(&args(&[
+      "--url",
+      "http://komodo",
+      "--key",
+      "arg-key",
+      "--key-file",
+      key.to_str().unwrap(),
+      "--secret-file",
+      secret.to_str().unwrap(),
+    ]))
+    .unwrap();
+    assert_eq!(creds.primary.key, "arg-file-key");
+    assert_eq!(creds.primary.secret, "arg-file-secret");
+  }
+
+  #[test]
+  fn host_key_and_secret_files_are_read() {
+    let key = temp_file("host-files", "key", "host-key");
+    let secret = temp_file("host-files", "secret", "host-secret");
+    let creds = temp_file(
+      "host-files",
+      "creds.toml",
+      &format!(
+        "url = \"http://primary\"\nkey = \"key\"\nsecret = \"secret\"\n\n[[hosts]]\nurl = \"http://standby\"\nkey_file = {key:?}\nsecret_file = {secret:?}\n"
+      ),
+    );
+    let creds =
+      load_creds(&args(&["--creds", creds.to_str().unwrap()]))
+        .unwrap();
+    assert_eq!(creds.hosts[0].key, "host-key");
+    assert_eq!(creds.hosts[0].secret, "host-secret");
+  }
+
+  #[test]
+  fn missing_key_is_an_error() {
+    let creds = temp_file(
+      "missing-key",
+      "creds.toml",
+      "url = \"http://komodo\"\nsecret = \"secret\"\n",
+    );
+    let e = load_creds(&args(&["--creds", creds.to_str().unwrap()]))
+      .unwrap_err();
+    let e = format!("{e:#}");
+    assert!(e.contains("Missing api key"), "{e}");
+    assert!(e.contains("key_file"), "{e}");
+  }
 }
@@ -0,0 +1,99 @@
+//! Runs the `komodo` binary against a minimal mock of Komodo Core,
+//! checking the exit codes scripts rely on.
+
+use std::{
+  io::{BufRead, BufReader, Read, Write},
+  net::{TcpListener, TcpStream},
+  process::{Command, Output},
+};
+
+/// Starts a mock Core which passes the version check on `/read`,
+/// and fails every `/execute` with Core's 'did not find any' error.
+/// Returns the mock Core url.
+fn mock_core() -> String {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let url = format!("http://{}", listener.local_addr().unwrap());
+  std::thread::spawn(move || {
+    for stream in listener.incoming().flatten() {
+      let _ = respond(stream);
+    }
+  });
+  url
+}
+
+fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+  let mut reader = BufReader::new(stream.try_clone()?);
+  let mut request_line = String::new();
+  reader.read_line(&mut request_line)?;
+
+  let mut content_length = 0;
+  loop {
+    let mut header = String::new();
+    reader.read_line(&mut header)?;
+    let header = header.trim_end();
+    if header.is_empty() {
+      break;
+    }
+    match header.split_once(':') {
+      Some((name, value))
+        if name.eq_ignore_ascii_case("content-length") =>
+      {
+        content_length = value.trim().parse().unwrap_or_default();
+      }
+      _ => {}
+    }
+  }
+  let mut body = vec![0; content_length];
+  reader.read_exact(&mut body)?;
+
+  let (status, body) = if request_line.starts_with("POST /read ") {
+    ("200 OK", r#"{"version":"1.18.4"}"#)
+  } else {
+    (
+      "500 Internal Server Error",
+      r#"{"error":"did not find any Build matching missing-build","trace":[]}"#,
+    )
+  };
+  write!(
+    stream,
+    "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+    body.len()
+  )
+}
+
+fn komodo(args: &[&str]) -> Output {
+  Command::new(env!("CARGO_BIN_EXE_komodo"))
+    .args(args)
+    .env("NO_COLOR", "1")
+    .output()
+    .unwrap()
+}
+
+#[test]
+fn exits_4_when_name_matches_nothing() {
+  let url = mock_core();
+  let output = komodo(&[
+    "--url",
+    &url,
+    "--key",
+    "key",
+    "--secret",
+    "secret",
+    "--yes",
+    "execute",
+    "run-build",
+    "missing-build",
+  ]);
+  assert_eq!(
+    output.status.code(),
+    Some(4),
+    "stderr: {}",
+    String::from_utf8_lossy(&output.stderr)
+  );
+}
+
+#[test]
+fn exits_2_on_invalid_arguments() {
+  let output = komodo(&["execute", "not-an-execution"]);
+  assert_eq!(output.status.code(), Some(2));
+}
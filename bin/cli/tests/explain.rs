@@ -0,0 +1,34 @@
+use std::process::Command;
+
+use serde_json::json;
+
+#[test]
+fn explain_prints_execution_without_contacting_core() {
+  // Nothing listens on port 1, so any request to Core would fail.
+  let output = Command::new(env!("CARGO_BIN_EXE_komodo"))
+    .args([
+      "--url",
+      "http://127.0.0.1:1",
+      "--key",
+      "key",
+      "--secret",
+      "secret",
+      "execute",
+      "--explain",
+      "run-build",
+      "test_build",
+    ])
+    .output()
+    .unwrap();
+  assert!(
+    output.status.success(),
+    "stderr: {}",
+    String::from_utf8_lossy(&output.stderr)
+  );
+  let explained: serde_json::Value =
+    serde_json::from_slice(&output.stdout).unwrap();
+  assert_eq!(
+    explained,
+    json!({ "type": "RunBuild", "params": { "build": "test_build" } })
+  );
+}
@@ -1,15 +1,18 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::Context;
 use futures_util::{TryStreamExt, future::join_all};
 use mungos::{
   init::MongoBuilder,
   mongodb::{
+    self, Collection,
     bson::{Document, RawDocumentBuf},
+    error::{ErrorKind, InsertManyError},
     options::InsertManyOptions,
   },
 };
 use serde::Deserialize;
+use tokio::sync::Semaphore;
 
 #[derive(Deserialize)]
 struct Env {
@@ -28,6 +31,24 @@ struct Env {
   /// Give the target database some time to initialize.
   #[serde(default = "default_startup_sleep_seconds")]
   startup_sleep_seconds: u64,
+  /// Max number of collections to copy at the same time.
+  /// Default: number of available CPUs
+  #[serde(default = "default_concurrency")]
+  concurrency: usize,
+  /// Only copy these collections (comma separated).
+  /// Default: empty (copy all collections)
+  #[serde(default)]
+  include: Vec<String>,
+  /// Skip these collections (comma separated).
+  /// Default: empty
+  #[serde(default)]
+  exclude: Vec<String>,
+  /// Skip documents which already exist on the target (by `_id`),
+  /// so an interrupted copy can be re-run without duplicate key errors.
+  /// Existing documents are not updated, even if they changed on the source.
+  /// Default: false
+  #[serde(default)]
+  resume: bool,
 }
 
 fn default_db_name() -> String {
@@ -38,10 +59,25 @@ fn default_startup_sleep_seconds() -> u64 {
   5
 }
 
+fn default_concurrency() -> usize {
+  std::thread::available_parallelism()
+    .map(|n| n.get())
+    .unwrap_or(4)
+}
+
 pub async fn main() -> anyhow::Result<()> {
-  let env = envy::from_env::<Env>()?;
+  let mut env = envy::from_env::<Env>()?;
+  env.include = trim_collections(env.include);
+  env.exclude = trim_collections(env.exclude);
+  if env.concurrency == 0 {
+    warn!("CONCURRENCY must be at least 1, using 1");
+    env.concurrency = 1;
+  }
 
-  info!("Sleeping for {} seconds...", env.startup_sleep_seconds);
+  info!(
+    seconds = env.startup_sleep_seconds,
+    "Sleeping before copy..."
+  );
   tokio::time::sleep(Duration::from_secs(env.startup_sleep_seconds))
     .await;
 
@@ -60,72 +96,284 @@ pub async fn main() -> anyhow::Result<()> {
     .context("Invalid SOURCE_URI")?
     .database(&env.target_db_name);
 
-  let mut handles = Vec::new();
-
-  for collection in source_db
+  let collections = source_db
     .list_collection_names()
     .await
-    .context("Failed to list collections on source db")?
-  {
+    .context("Failed to list collections on source db")?;
+
+  for collection in &env.include {
+    if !collections.contains(collection) {
+      warn!(
+        collection = %collection,
+        "Included collection not found on source db"
+      );
+    }
+  }
+
+  let collections = collections.into_iter().filter(|collection| {
+    should_copy(collection, &env.include, &env.exclude)
+  });
+
+  let resume = env.resume;
+  run_concurrent(collections, env.concurrency, |collection| {
     let source = source_db.collection::<RawDocumentBuf>(&collection);
     let target = target_db.collection::<RawDocumentBuf>(&collection);
-
-    handles.push(tokio::spawn(async move {
-      let res = async {
-        let mut buffer = Vec::<RawDocumentBuf>::new();
-        let mut count = 0;
-        let mut cursor = source
-          .find(Document::new())
-          .await
-          .context("Failed to query source collection")?;
-        while let Some(doc) = cursor
-          .try_next()
-          .await
-          .context("Failed to get next document")?
-        {
-          count += 1;
-          buffer.push(doc);
-          if buffer.len() >= 20_000 {
-            if let Err(e) = target
-              .insert_many(&buffer)
-              .with_options(
-                InsertManyOptions::builder().ordered(false).build(),
-              )
-              .await
-            {
-              error!("Failed to flush document batch in {collection} collection | {e:#}");
-            };
-            buffer.clear();
-          }
-        }
-        if !buffer.is_empty() {
-          target
-            .insert_many(&buffer)
-            .with_options(
-              InsertManyOptions::builder().ordered(false).build(),
-            )
-            .await
-            .context("Failed to flush documents")?;
-        }
-        anyhow::Ok(count)
-      }
-      .await;
-      match res {
+    async move {
+      match copy_collection(&source, &target, resume).await {
         Ok(count) => {
           if count > 0 {
-            info!("Finished copying {collection} collection | Copied {count}");
+            info!(
+              collection = %collection,
+              count,
+              "Finished copying collection"
+            );
           }
         }
         Err(e) => {
-          error!("Failed to copy {collection} collection | {e:#}")
+          error!(
+            collection = %collection,
+            error = %format!("{e:#}"),
+            "Failed to copy collection"
+          )
         }
       }
-    }));
+    }
+  })
+  .await;
+
+  info!("Finished copying database ✅");
+
+  Ok(())
+}
+
+/// Copies every document in the source collection to the target,
+/// returning the number of documents copied.
+async fn copy_collection(
+  source: &Collection<RawDocumentBuf>,
+  target: &Collection<RawDocumentBuf>,
+  resume: bool,
+) -> anyhow::Result<usize> {
+  let mut buffer = Vec::<RawDocumentBuf>::new();
+  let mut count = 0;
+  let mut cursor = source
+    .find(Document::new())
+    .await
+    .context("Failed to query source collection")?;
+  while let Some(doc) = cursor
+    .try_next()
+    .await
+    .context("Failed to get next document")?
+  {
+    count += 1;
+    buffer.push(doc);
+    if buffer.len() >= 20_000 {
+      if let Err(e) = flush(target, &buffer, resume).await {
+        error!(
+          collection = target.name(),
+          error = %format!("{e:#}"),
+          "Failed to flush document batch"
+        );
+      };
+      buffer.clear();
+    }
   }
+  if !buffer.is_empty() {
+    flush(target, &buffer, resume)
+      .await
+      .context("Failed to flush documents")?;
+  }
+  Ok(count)
+}
 
+/// Spawns a task for each item, with at most `concurrency`
+/// of them running at the same time.
+async fn run_concurrent<T, Fut>(
+  items: impl IntoIterator<Item = T>,
+  concurrency: usize,
+  task: impl Fn(T) -> Fut,
+) where
+  Fut: Future<Output = ()> + Send + 'static,
+{
+  let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+  let handles = items
+    .into_iter()
+    .map(|item| {
+      let semaphore = semaphore.clone();
+      let task = task(item);
+      tokio::spawn(async move {
+        // Limits the number of open cursors at any one time
+        let _permit = semaphore.acquire_owned().await;
+        task.await
+      })
+    })
+    .collect::<Vec<_>>();
   join_all(handles).await;
+}
 
-  info!("Finished copying database ✅");
+fn trim_collections(collections: Vec<String>) -> Vec<String> {
+  collections
+    .into_iter()
+    .map(|collection| collection.trim().to_string())
+    .filter(|collection| !collection.is_empty())
+    .collect()
+}
 
-  Ok(())
+/// An empty `include` copies every collection not in `exclude`.
+fn should_copy(
+  collection: &str,
+  include: &[String],
+  exclude: &[String],
+) -> bool {
+  (include.is_empty() || include.iter().any(|c| c == collection))
+    && !exclude.iter().any(|c| c == collection)
+}
+
+async fn flush(
+  target: &Collection<RawDocumentBuf>,
+  buffer: &[RawDocumentBuf],
+  resume: bool,
+) -> anyhow::Result<()> {
+  let res = target
+    .insert_many(buffer)
+    .with_options(InsertManyOptions::builder().ordered(false).build())
+    .await;
+  match res {
+    Ok(_) => Ok(()),
+    // Unordered inserts still insert the rest of the batch,
+    // so documents copied by a previous run only fail as duplicates.
+    // Note. These existing documents are skipped, not updated,
+    // so changes made on the source since the last run are not copied.
+    Err(e) if resume && only_duplicate_key_errors(&e) => Ok(()),
+    Err(e) => Err(e.into()),
+  }
+}
+
+/// Mongo error code for a duplicate key write error.
+const DUPLICATE_KEY_ERROR_CODE: i32 = 11000;
+
+fn only_duplicate_key_errors(e: &mongodb::error::Error) -> bool {
+  let ErrorKind::InsertMany(InsertManyError {
+    write_errors: Some(write_errors),
+    write_concern_error: None,
+    ..
+  }) = e.kind.as_ref()
+  else {
+    return false;
+  };
+  write_errors
+    .iter()
+    .all(|e| e.code == DUPLICATE_KEY_ERROR_CODE)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  use mungos::mongodb::bson::doc;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn concurrency_one_runs_tasks_one_at_a_time() {
+    let active = Arc::new(AtomicUsize::new(0));
+    let max_active = Arc::new(AtomicUsize::new(0));
+    let finished = Arc::new(AtomicUsize::new(0));
+    run_concurrent(0..5, 1, |_| {
+      let active = active.clone();
+      let max_active = max_active.clone();
+      let finished = finished.clone();
+      async move {
+        let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+        max_active.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        active.fetch_sub(1, Ordering::SeqCst);
+        finished.fetch_add(1, Ordering::SeqCst);
+      }
+    })
+    .await;
+    assert_eq!(max_active.load(Ordering::SeqCst), 1);
+    assert_eq!(finished.load(Ordering::SeqCst), 5);
+  }
+
+  fn collections(collections: &[&str]) -> Vec<String> {
+    collections.iter().map(|c| c.to_string()).collect()
+  }
+
+  #[test]
+  fn empty_include_copies_all_but_excluded() {
+    let exclude = collections(&["Stats"]);
+    assert!(should_copy("Server", &[], &exclude));
+    assert!(should_copy("Update", &[], &exclude));
+    assert!(!should_copy("Stats", &[], &exclude));
+  }
+
+  #[test]
+  fn include_copies_only_subset() {
+    let include = collections(&["Server", "Stack"]);
+    assert!(should_copy("Server", &include, &[]));
+    assert!(should_copy("Stack", &include, &[]));
+    assert!(!should_copy("Update", &include, &[]));
+  }
+
+  #[test]
+  fn exclude_wins_over_include() {
+    let include = collections(&["Server", "Stack"]);
+    let exclude = collections(&["Stack"]);
+    assert!(should_copy("Server", &include, &exclude));
+    assert!(!should_copy("Stack", &include, &exclude));
+  }
+
+  #[test]
+  fn trims_collection_names() {
+    let include =
+      trim_collections(collections(&["Server", " Stack ", " ", ""]));
+    assert_eq!(include, collections(&["Server", "Stack"]));
+    assert!(should_copy("Stack", &include, &[]));
+  }
+
+  #[tokio::test]
+  #[ignore = "requires a MongoDB at KOMODO_UTIL_TEST_MONGO_URI"]
+  async fn resume_copy_twice_leaves_target_unchanged() {
+    let uri = std::env::var("KOMODO_UTIL_TEST_MONGO_URI").unwrap();
+    let db = MongoBuilder::default()
+      .uri(uri)
+      .build()
+      .await
+      .unwrap()
+      .database("komodo_util_test_resume");
+    db.drop().await.unwrap();
+
+    let expected = vec![
+      doc! { "_id": 1, "name": "server-1" },
+      doc! { "_id": 2, "name": "server-2" },
+    ];
+    db.collection::<Document>("source")
+      .insert_many(&expected)
+      .await
+      .unwrap();
+
+    let source = db.collection::<RawDocumentBuf>("source");
+    let target = db.collection::<RawDocumentBuf>("target");
+    assert_eq!(
+      copy_collection(&source, &target, true).await.unwrap(),
+      2
+    );
+    assert_eq!(
+      copy_collection(&source, &target, true).await.unwrap(),
+      2
+    );
+
+    let copied = db
+      .collection::<Document>("target")
+      .find(Document::new())
+      .sort(doc! { "_id": 1 })
+      .await
+      .unwrap()
+      .try_collect::<Vec<_>>()
+      .await
+      .unwrap();
+    assert_eq!(copied, expected);
+
+    db.drop().await.unwrap();
+  }
 }
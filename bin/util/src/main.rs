@@ -11,17 +11,33 @@ enum Mode {
   CopyDatabase,
 }
 
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum LogFormat {
+  #[default]
+  Standard,
+  Json,
+}
+
 #[derive(Deserialize)]
 struct Env {
   mode: Mode,
+  /// Log as human readable text (standard) or json.
+  /// Default: standard
+  #[serde(default)]
+  komodo_util_log_format: LogFormat,
 }
 
 async fn app() -> anyhow::Result<()> {
   dotenvy::dotenv().ok();
-  tracing_subscriber::fmt::init();
 
   let env = envy::from_env::<Env>()?;
 
+  match env.komodo_util_log_format {
+    LogFormat::Standard => tracing_subscriber::fmt::init(),
+    LogFormat::Json => tracing_subscriber::fmt().json().init(),
+  }
+
   info!("Komodo Util version: v{}", env!("CARGO_PKG_VERSION"));
   info!("Mode: {:?}", env.mode);
 
@@ -8,7 +8,7 @@ use komodo_client::entities::{
   update::Log,
 };
 
-use crate::get_commit_hash_log;
+use crate::{get_commit_hash_log, sanitize_log};
 
 /// Will delete the existing repo folder,
 /// clone the repo, get the latest hash / message,
@@ -28,8 +28,25 @@ pub async fn clone<T>(
 where
   T: Into<RepoExecutionArgs> + std::fmt::Debug,
 {
-  let args: RepoExecutionArgs = clone_args.into();
-  let repo_url = args.remote_url(access_token.as_deref())?;
+  let mut res = clone_inner(
+    clone_args.into(),
+    root_repo_dir,
+    access_token.as_deref(),
+  )
+  .await?;
+  // Sanitize the output of every command run
+  for log in &mut res.logs {
+    sanitize_log(log, access_token.as_deref());
+  }
+  Ok(res)
+}
+
+async fn clone_inner(
+  args: RepoExecutionArgs,
+  root_repo_dir: &Path,
+  access_token: Option<&str>,
+) -> anyhow::Result<RepoExecutionResponse> {
+  let repo_url = args.remote_url(access_token)?;
 
   let mut res = RepoExecutionResponse {
     path: args.path(root_repo_dir),
@@ -75,14 +92,7 @@ where
     args.branch
   );
 
-  let mut log = run_komodo_command("Clone Repo", None, command).await;
-
-  if let Some(token) = access_token {
-    log.command = log.command.replace(&token, "<TOKEN>");
-    log.stdout = log.stdout.replace(&token, "<TOKEN>");
-    log.stderr = log.stderr.replace(&token, "<TOKEN>");
-  }
-
+  let log = run_komodo_command("Clone Repo", None, command).await;
   res.logs.push(log);
 
   if !all_logs_success(&res.logs) {
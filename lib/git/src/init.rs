@@ -6,11 +6,68 @@ use komodo_client::entities::{
   RepoExecutionArgs, all_logs_success, update::Log,
 };
 
+use crate::sanitize_log;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InitRepoOptions<'a> {
+  /// After setting up the branch, commit any existing files
+  /// with this message. The commit is allowed to be empty,
+  /// so the branch always has some history.
+  pub initial_commit: Option<&'a str>,
+  /// The initial commit author name. Default: `komodo`
+  pub author_name: Option<&'a str>,
+  /// The initial commit author email. Default: `komodo@komo.do`
+  pub author_email: Option<&'a str>,
+}
+
 pub async fn init_folder_as_repo(
   folder_path: &Path,
   args: &RepoExecutionArgs,
   access_token: Option<&str>,
   logs: &mut Vec<Log>,
+) {
+  init_folder_as_repo_with_options(
+    folder_path,
+    args,
+    access_token,
+    Default::default(),
+    logs,
+  )
+  .await
+}
+
+/// Same as [init_folder_as_repo], but can also create an initial commit.
+///
+/// Note. Don't use the initial commit when pulling right after init,
+/// `git pull --rebase` will replay it on top of the remote branch.
+pub async fn init_folder_as_repo_with_options(
+  folder_path: &Path,
+  args: &RepoExecutionArgs,
+  access_token: Option<&str>,
+  options: InitRepoOptions<'_>,
+  logs: &mut Vec<Log>,
+) {
+  let start = logs.len();
+  init_folder_as_repo_inner(
+    folder_path,
+    args,
+    access_token,
+    options,
+    logs,
+  )
+  .await;
+  // Sanitize the output of every command run
+  for log in &mut logs[start..] {
+    sanitize_log(log, access_token);
+  }
+}
+
+async fn init_folder_as_repo_inner(
+  folder_path: &Path,
+  args: &RepoExecutionArgs,
+  access_token: Option<&str>,
+  options: InitRepoOptions<'_>,
+  logs: &mut Vec<Log>,
 ) {
   // let folder_path = args.path(repo_dir);
   // Initialize the folder as a git repo
@@ -31,18 +88,12 @@ pub async fn init_folder_as_repo(
   };
 
   // Set remote url
-  let mut set_remote = run_komodo_command(
+  let set_remote = run_komodo_command(
     "Add git remote",
     folder_path,
     format!("git remote add origin {repo_url}"),
   )
   .await;
-  // Sanitize the output
-  if let Some(token) = &access_token {
-    set_remote.command = set_remote.command.replace(token, "<TOKEN>");
-    set_remote.stdout = set_remote.stdout.replace(token, "<TOKEN>");
-    set_remote.stderr = set_remote.stderr.replace(token, "<TOKEN>");
-  }
   if !set_remote.success {
     logs.push(set_remote);
     return;
@@ -57,5 +108,163 @@ pub async fn init_folder_as_repo(
   .await;
   if !init_repo.success {
     logs.push(init_repo);
+    return;
+  }
+
+  // Track the remote branch, so plain `git push` / `git pull` work.
+  // This is set directly in the config rather than with
+  // `git branch --set-upstream-to`, as the remote branch
+  // may not exist yet. In that case it is created on first push.
+  let set_upstream = run_komodo_command(
+    "Set Upstream",
+    folder_path,
+    format!(
+      "git config branch.{branch}.remote origin && git config branch.{branch}.merge refs/heads/{branch}",
+      branch = args.branch
+    ),
+  )
+  .await;
+  logs.push(set_upstream);
+  if !all_logs_success(logs) {
+    return;
+  }
+
+  let Some(message) = options.initial_commit else {
+    return;
+  };
+
+  let add_files =
+    run_komodo_command("Add Files", folder_path, "git add -A").await;
+  if !add_files.success {
+    logs.push(add_files);
+    return;
+  }
+
+  // The author is passed with `-c` so the global git config is untouched.
+  let commit = run_komodo_command(
+    "Initial Commit",
+    folder_path,
+    format!(
+      "git -c user.name=\"{}\" -c user.email=\"{}\" commit --allow-empty -m \"[Komodo] {message}\"",
+      options.author_name.unwrap_or("komodo"),
+      options.author_email.unwrap_or("komodo@komo.do"),
+    ),
+  )
+  .await;
+  logs.push(commit);
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use komodo_client::entities::DefaultRepoFolder;
+  use run_command::async_run_command;
+
+  use super::*;
+
+  fn repo_args(branch: &str) -> RepoExecutionArgs {
+    RepoExecutionArgs {
+      name: String::from("test"),
+      provider: String::from("github.com"),
+      https: true,
+      account: None,
+      repo: Some(String::from("komodo/test")),
+      branch: branch.to_string(),
+      commit: None,
+      destination: None,
+      default_folder: DefaultRepoFolder::NotApplicable,
+    }
+  }
+
+  /// A fresh, empty folder under the system temp dir.
+  fn temp_folder(name: &str) -> PathBuf {
+    let folder = std::env::temp_dir()
+      .join(format!("komodo-git-init-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&folder);
+    std::fs::create_dir_all(&folder).unwrap();
+    folder
+  }
+
+  async fn git(folder: &Path, command: &str) -> String {
+    let output = async_run_command(&format!(
+      "cd {} && {command}",
+      folder.display()
+    ))
+    .await;
+    assert!(output.success(), "{command} | {}", output.stderr);
+    output.stdout.trim().to_string()
+  }
+
+  #[tokio::test]
+  async fn creates_initial_commit() {
+    let folder = temp_folder("initial-commit");
+    std::fs::write(folder.join("compose.yaml"), "services: {}\n")
+      .unwrap();
+    let mut logs = Vec::new();
+    init_folder_as_repo_with_options(
+      &folder,
+      &repo_args("main"),
+      None,
+      InitRepoOptions {
+        initial_commit: Some("Initialize repo"),
+        author_name: Some("Test Author"),
+        author_email: Some("test@komo.do"),
+      },
+      &mut logs,
+    )
+    .await;
+    assert!(all_logs_success(&logs), "{logs:?}");
+
+    assert_eq!(
+      git(&folder, "git log -1 --pretty='%an <%ae> %s'").await,
+      "Test Author <test@komo.do> [Komodo] Initialize repo"
+    );
+    assert_eq!(git(&folder, "git ls-files").await, "compose.yaml");
+
+    std::fs::remove_dir_all(&folder).unwrap();
+  }
+
+  #[tokio::test]
+  async fn tracks_remote_branch() {
+    let folder = temp_folder("upstream");
+    let mut logs = Vec::new();
+    init_folder_as_repo(
+      &folder,
+      &repo_args("feature"),
+      None,
+      &mut logs,
+    )
+    .await;
+    assert!(all_logs_success(&logs), "{logs:?}");
+
+    assert_eq!(
+      git(&folder, "git config branch.feature.remote").await,
+      "origin"
+    );
+    assert_eq!(
+      git(&folder, "git config branch.feature.merge").await,
+      "refs/heads/feature"
+    );
+
+    std::fs::remove_dir_all(&folder).unwrap();
+  }
+
+  #[tokio::test]
+  async fn no_commit_by_default() {
+    let folder = temp_folder("no-commit");
+    let mut logs = Vec::new();
+    init_folder_as_repo(&folder, &repo_args("main"), None, &mut logs)
+      .await;
+    assert!(all_logs_success(&logs), "{logs:?}");
+
+    let output = async_run_command(&format!(
+      "cd {} && git rev-parse HEAD",
+      folder.display()
+    ))
+    .await;
+    assert!(!output.success());
+
+    std::fs::remove_dir_all(&folder).unwrap();
   }
 }
@@ -17,11 +17,29 @@ mod pull_or_clone;
 pub use crate::{
   clone::clone,
   commit::{commit_all, commit_file, write_commit_file},
-  init::init_folder_as_repo,
+  init::{
+    InitRepoOptions, init_folder_as_repo,
+    init_folder_as_repo_with_options,
+  },
   pull::pull,
   pull_or_clone::pull_or_clone,
 };
 
+/// Replaces any occurence of the access token
+/// in the log command / stdout / stderr with `<TOKEN>`.
+pub(crate) fn sanitize_log(
+  log: &mut Log,
+  access_token: Option<&str>,
+) {
+  let Some(token) = access_token.filter(|token| !token.is_empty())
+  else {
+    return;
+  };
+  log.command = log.command.replace(token, "<TOKEN>");
+  log.stdout = log.stdout.replace(token, "<TOKEN>");
+  log.stderr = log.stderr.replace(token, "<TOKEN>");
+}
+
 #[instrument(level = "debug")]
 pub async fn get_commit_hash_info(
   repo_dir: &Path,
@@ -107,3 +125,46 @@ pub async fn get_remote_url(path: &Path) -> anyhow::Result<String> {
     ))
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use komodo_client::entities::update::Log;
+
+  use super::sanitize_log;
+
+  fn log() -> Log {
+    Log {
+      command: String::from(
+        "git clone https://token123@github.com/org/repo",
+      ),
+      stdout: String::from("Cloning with token123"),
+      stderr: String::from("fatal: token123 token123"),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn replaces_token_everywhere() {
+    let mut log = log();
+    sanitize_log(&mut log, Some("token123"));
+    assert_eq!(
+      log.command,
+      "git clone https://<TOKEN>@github.com/org/repo"
+    );
+    assert_eq!(log.stdout, "Cloning with <TOKEN>");
+    assert_eq!(log.stderr, "fatal: <TOKEN> <TOKEN>");
+  }
+
+  #[test]
+  fn ignores_missing_or_empty_token() {
+    let mut none = log();
+    sanitize_log(&mut none, None);
+    assert_eq!(none.stdout, log().stdout);
+
+    let mut empty = log();
+    sanitize_log(&mut empty, Some(""));
+    assert_eq!(empty.command, log().command);
+    assert_eq!(empty.stdout, log().stdout);
+    assert_eq!(empty.stderr, log().stderr);
+  }
+}
@@ -11,7 +11,7 @@ use komodo_client::entities::{
   komodo_timestamp, update::Log,
 };
 
-use crate::get_commit_hash_log;
+use crate::{get_commit_hash_log, sanitize_log};
 
 /// Wait this long after a pull to allow another pull through
 const PULL_TIMEOUT: i64 = 5_000;
@@ -80,21 +80,12 @@ where
     }
 
     // Set remote url
-    let mut set_remote = run_komodo_command(
+    let set_remote = run_komodo_command(
       "Set Git Remote",
       res.path.as_ref(),
       format!("git remote set-url origin {repo_url}"),
     )
     .await;
-    // Sanitize the output
-    if let Some(token) = access_token {
-      set_remote.command =
-        set_remote.command.replace(&token, "<TOKEN>");
-      set_remote.stdout =
-        set_remote.stdout.replace(&token, "<TOKEN>");
-      set_remote.stderr =
-        set_remote.stderr.replace(&token, "<TOKEN>");
-    }
     res.logs.push(set_remote);
     if !all_logs_success(&res.logs) {
       return Ok(res);
@@ -167,6 +158,14 @@ where
   }
   .await;
 
+  // Sanitize the output
+  let res = res.map(|mut res| {
+    for log in &mut res.logs {
+      sanitize_log(log, access_token.as_deref());
+    }
+    res
+  });
+
   // Set the cache with results. Any other calls waiting on the lock will
   // then immediately also use this same result.
   locked.set(&res, komodo_timestamp());